@@ -1,11 +1,41 @@
-extern crate rand;
-use rand::{thread_rng, Rng};
-
+use std::collections::VecDeque;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 const ROUND_LENGTH: usize = 100;
 const NUM_ROUNDS: usize = 100;
 
+// Seed for the tournament's RNG. Fixed so that the tournament rankings are
+// bit-identical across invocations; change it to explore a different draw.
+// The simulated-annealing search stops on a wall-clock budget, so its result,
+// and the replicator run that draws from the RNG after it, vary between runs.
+const SEED: u64 = 88172645463325252;
+
+// Seedable PRNG used in place of rand::thread_rng(), so a tournament can be
+// replayed bit-for-bit from a seed.
+#[derive(Clone, Debug)]
+struct XorShift {
+    state: u64,
+}
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        assert!(seed != 0, "XorShift requires a nonzero seed");
+        Self { state: seed }
+    }
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+    // Draws a value uniformly in [0, 1).
+    fn gen_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Move {
     Cooperate,
@@ -18,10 +48,10 @@ impl Move {
             Move::Defect => Move::Cooperate,
         }
     }
-    fn flip(self, prob: f64) -> Move {
+    fn flip(self, prob: f64, rng: &mut XorShift) -> Move {
         assert!(0. <= prob);
         assert!(0.5 >= prob);
-        if thread_rng().gen_range(0., 1.) < prob {
+        if rng.gen_f64() < prob {
             self.opposite()
         } else {
             self
@@ -38,18 +68,26 @@ fn single_score(m1: Move, m2: Move) -> (u64, u64) {
     }
 }
 
+// A strategy. Stateless; each game gets its own PlayerState via new_game so
+// per-game totals don't leak between opponents.
 trait Player: fmt::Debug {
-    fn play(&self, my_moves: &Vec<Move>, their_noisy_moves: &Vec<Move>) -> Move;
+    fn new_game(&self) -> Box<dyn PlayerState>;
+}
+
+// The running state of a single player across one game.
+trait PlayerState {
+    fn play(&mut self, rng: &mut XorShift) -> Move;
+    fn observe(&mut self, my_move: Move, their_noisy_move: Move);
 }
 
 // Output format is:
 // let scores = play_all_pairs(players)
 // scores[p1][p2] = <p1's score when playing against p2>
-fn play_all_pairs(players: &Vec<&Player>) -> Vec<Vec<u64>> {
+fn play_all_pairs(players: &Vec<&Player>, rng: &mut XorShift) -> Vec<Vec<u64>> {
     let mut scores = vec![vec![0; players.len()]; players.len()];
     for i in 0..players.len() {
         for j in 0..i + 1 {
-            let (p1_score, p2_score) = play_pair(players[i], players[j]);
+            let (p1_score, p2_score) = play_pair(players[i], players[j], rng);
             scores[i][j] = p1_score;
             scores[j][i] = p2_score;
         }
@@ -57,33 +95,30 @@ fn play_all_pairs(players: &Vec<&Player>) -> Vec<Vec<u64>> {
     scores
 }
 
-fn play_pair(p1: &Player, p2: &Player) -> (u64, u64) {
-    let (res1, res2): (Vec<u64>, Vec<u64>) = (0..NUM_ROUNDS).map(|_| play_round(p1, p2)).unzip();
+fn play_pair(p1: &Player, p2: &Player, rng: &mut XorShift) -> (u64, u64) {
+    let (res1, res2): (Vec<u64>, Vec<u64>) =
+        (0..NUM_ROUNDS).map(|_| play_round(p1, p2, rng)).unzip();
     (res1.iter().sum(), res2.iter().sum())
 }
 
-fn play_round(p1: &Player, p2: &Player) -> (u64, u64) {
-    let prob = thread_rng().gen_range(0., 0.5);
-    let mut moves1 = vec![];
-    let mut noisy1 = vec![];
+fn play_round(p1: &Player, p2: &Player, rng: &mut XorShift) -> (u64, u64) {
+    let prob = rng.gen_f64() * 0.5;
+    let mut state1 = p1.new_game();
+    let mut state2 = p2.new_game();
     let mut score1 = 0;
-    let mut moves2 = vec![];
-    let mut noisy2 = vec![];
     let mut score2 = 0;
     for _ in 0..ROUND_LENGTH {
-        let move1 = p1.play(&moves1, &noisy2);
-        let move2 = p2.play(&moves2, &noisy1);
-        let noise1 = move1.flip(prob);
-        let noise2 = move2.flip(prob);
+        let move1 = state1.play(rng);
+        let move2 = state2.play(rng);
+        let noise1 = move1.flip(prob, rng);
+        let noise2 = move2.flip(prob, rng);
 
         let (ss1, ss2) = single_score(noise1, noise2);
         score1 += ss1;
         score2 += ss2;
 
-        moves1.push(move1);
-        moves2.push(move2);
-        noisy1.push(noise1);
-        noisy2.push(noise2);
+        state1.observe(move1, noise2);
+        state2.observe(move2, noise1);
     }
     (score1, score2)
 }
@@ -126,13 +161,25 @@ impl Constant {
     }
 }
 impl Player for Constant {
-    fn play(&self, _my_moves: &Vec<Move>, _their_noisy_moves: &Vec<Move>) -> Move {
-        if thread_rng().gen_range(0., 1.) < self.cooperate_prob {
+    fn new_game(&self) -> Box<dyn PlayerState> {
+        Box::new(ConstantState {
+            cooperate_prob: self.cooperate_prob,
+        })
+    }
+}
+
+struct ConstantState {
+    cooperate_prob: f64,
+}
+impl PlayerState for ConstantState {
+    fn play(&mut self, rng: &mut XorShift) -> Move {
+        if rng.gen_f64() < self.cooperate_prob {
             Move::Cooperate
         } else {
             Move::Defect
         }
     }
+    fn observe(&mut self, _my_move: Move, _their_noisy_move: Move) {}
 }
 
 #[derive(Debug)]
@@ -146,18 +193,33 @@ impl TitForTat {
     }
 }
 impl Player for TitForTat {
-    fn play(&self, _my_moves: &Vec<Move>, their_noisy_moves: &Vec<Move>) -> Move {
-        if their_noisy_moves.len() < self.delay {
-            self.default
+    fn new_game(&self) -> Box<dyn PlayerState> {
+        Box::new(TitForTatState {
+            default: self.default,
+            delay: self.delay,
+            recent: VecDeque::with_capacity(self.delay),
+        })
+    }
+}
+
+struct TitForTatState {
+    default: Move,
+    delay: usize,
+    // The last `delay` noisy moves observed from the opponent.
+    recent: VecDeque<Move>,
+}
+impl PlayerState for TitForTatState {
+    fn play(&mut self, _rng: &mut XorShift) -> Move {
+        if self.recent.len() == self.delay && self.recent.iter().all(|&m| m == self.default) {
+            self.default.opposite()
         } else {
-            if their_noisy_moves[their_noisy_moves.len() - self.delay..]
-                .iter()
-                .any(|&m| m != self.default)
-            {
-                self.default
-            } else {
-                self.default.opposite()
-            }
+            self.default
+        }
+    }
+    fn observe(&mut self, _my_move: Move, their_noisy_move: Move) {
+        self.recent.push_back(their_noisy_move);
+        if self.recent.len() > self.delay {
+            self.recent.pop_front();
         }
     }
 }
@@ -173,14 +235,28 @@ impl Threshold {
     }
 }
 impl Player for Threshold {
-    fn play(&self, _my_moves: &Vec<Move>, their_noisy_moves: &Vec<Move>) -> Move {
-        if their_noisy_moves.len() < self.start {
+    fn new_game(&self) -> Box<dyn PlayerState> {
+        Box::new(ThresholdState {
+            start: self.start,
+            coop_thresh: self.coop_thresh,
+            coop_count: 0,
+            total: 0,
+        })
+    }
+}
+
+struct ThresholdState {
+    start: usize,
+    coop_thresh: f64,
+    coop_count: usize,
+    total: usize,
+}
+impl PlayerState for ThresholdState {
+    fn play(&mut self, _rng: &mut XorShift) -> Move {
+        if self.total < self.start {
             Move::Cooperate
         } else {
-            let freq = their_noisy_moves
-                .iter()
-                .filter(|&&m| m == Move::Cooperate)
-                .count() as f64 / their_noisy_moves.len() as f64;
+            let freq = self.coop_count as f64 / self.total as f64;
             if freq >= self.coop_thresh {
                 Move::Cooperate
             } else {
@@ -188,10 +264,326 @@ impl Player for Threshold {
             }
         }
     }
+    fn observe(&mut self, _my_move: Move, their_noisy_move: Move) {
+        self.total += 1;
+        if their_noisy_move == Move::Cooperate {
+            self.coop_count += 1;
+        }
+    }
+}
+
+const NUM_STATES: usize = 4;
+
+// Indexes the four conditional cooperation probabilities of a memory-one
+// reactive strategy by the previous round's (my_move, their_noisy_move).
+fn reactive_state_index(my_last: Move, their_last: Move) -> usize {
+    match (my_last, their_last) {
+        (Move::Cooperate, Move::Cooperate) => 0,
+        (Move::Cooperate, Move::Defect) => 1,
+        (Move::Defect, Move::Cooperate) => 2,
+        (Move::Defect, Move::Defect) => 3,
+    }
+}
+
+// A memory-one reactive player: cooperates with a probability depending only
+// on the previous round's outcome. probs[0] doubles as the opening-move
+// cooperation probability, since there's no previous round yet.
+#[derive(Debug, Clone)]
+struct Reactive {
+    probs: [f64; 4],
+}
+impl Reactive {
+    fn new(probs: [f64; 4]) -> Self {
+        Self { probs }
+    }
+    fn random(rng: &mut XorShift) -> Self {
+        Self::new([rng.gen_f64(), rng.gen_f64(), rng.gen_f64(), rng.gen_f64()])
+    }
+    // Perturbs one randomly chosen coordinate by a small uniform delta.
+    fn perturb(&self, rng: &mut XorShift, step: f64) -> Self {
+        let mut probs = self.probs;
+        let i = (rng.gen_f64() * probs.len() as f64) as usize;
+        let delta = (rng.gen_f64() - 0.5) * 2. * step;
+        probs[i] = (probs[i] + delta).clamp(0., 1.);
+        Self::new(probs)
+    }
+}
+impl Player for Reactive {
+    fn new_game(&self) -> Box<dyn PlayerState> {
+        Box::new(ReactiveState {
+            probs: self.probs,
+            last: None,
+        })
+    }
+}
+
+struct ReactiveState {
+    probs: [f64; 4],
+    last: Option<(Move, Move)>,
+}
+impl PlayerState for ReactiveState {
+    fn play(&mut self, rng: &mut XorShift) -> Move {
+        let prob = match self.last {
+            None => self.probs[0],
+            Some((my_last, their_last)) => self.probs[reactive_state_index(my_last, their_last)],
+        };
+        if rng.gen_f64() < prob {
+            Move::Cooperate
+        } else {
+            Move::Defect
+        }
+    }
+    fn observe(&mut self, my_move: Move, their_noisy_move: Move) {
+        self.last = Some((my_move, their_noisy_move));
+    }
+}
+
+// A player that estimates the opponent as a memory-one Markov strategy and
+// picks its move by finite-horizon backward induction against that model.
+// assumed_noise is the player's fixed estimate of the hidden per-round flip
+// probability.
+#[derive(Debug, Clone)]
+struct BestResponse {
+    assumed_noise: f64,
+}
+impl BestResponse {
+    fn new(assumed_noise: f64) -> Self {
+        Self { assumed_noise }
+    }
+}
+// How often (in rounds) BestResponseState re-derives its opponent model and
+// re-runs backward induction. Each refresh rebuilds the value table for all
+// remaining rounds, so a full game is still O(ROUND_LENGTH^2); refreshing
+// only every few rounds and otherwise reading off the cached value table cuts
+// that work by about a factor of MODEL_REFRESH_INTERVAL, at the cost of
+// playing a few rounds behind the latest data.
+const MODEL_REFRESH_INTERVAL: usize = 10;
+
+impl Player for BestResponse {
+    fn new_game(&self) -> Box<dyn PlayerState> {
+        Box::new(BestResponseState {
+            assumed_noise: self.assumed_noise,
+            coop_counts: [1.; NUM_STATES],
+            total_counts: [2.; NUM_STATES],
+            last_state: None,
+            round: 0,
+            cached_model: [0.5; NUM_STATES],
+            value_table: Vec::new(),
+        })
+    }
+}
+
+struct BestResponseState {
+    assumed_noise: f64,
+    // Laplace-smoothed transition counts: coop_counts[s] / total_counts[s]
+    // estimates P(their next noisy move is Cooperate | previous state s).
+    coop_counts: [f64; NUM_STATES],
+    total_counts: [f64; NUM_STATES],
+    last_state: Option<usize>,
+    round: usize,
+    // The model and value table as of the last refresh; value_table[h] is
+    // the DP value of having h rounds left to play under cached_model.
+    cached_model: [f64; NUM_STATES],
+    value_table: Vec<[f64; NUM_STATES]>,
+}
+impl BestResponseState {
+    fn model(&self) -> [f64; NUM_STATES] {
+        let mut model = [0.; NUM_STATES];
+        for ((m, &coop), &total) in model.iter_mut().zip(&self.coop_counts).zip(&self.total_counts) {
+            *m = coop / total;
+        }
+        model
+    }
+}
+impl PlayerState for BestResponseState {
+    fn play(&mut self, _rng: &mut XorShift) -> Move {
+        let rounds_remaining = ROUND_LENGTH - self.round;
+        if self.round % MODEL_REFRESH_INTERVAL == 0 {
+            self.cached_model = self.model();
+            self.value_table = best_response_value_table(&self.cached_model, self.assumed_noise, rounds_remaining);
+        }
+        let current_state =
+            self.last_state
+                .unwrap_or_else(|| reactive_state_index(Move::Cooperate, Move::Cooperate));
+        let continuation = &self.value_table[rounds_remaining - 1];
+        best_response_choose(&self.cached_model, self.assumed_noise, continuation, current_state).0
+    }
+    fn observe(&mut self, my_move: Move, their_noisy_move: Move) {
+        if let Some(prev_state) = self.last_state {
+            self.total_counts[prev_state] += 1.;
+            if their_noisy_move == Move::Cooperate {
+                self.coop_counts[prev_state] += 1.;
+            }
+        }
+        self.last_state = Some(reactive_state_index(my_move, their_noisy_move));
+        self.round += 1;
+    }
+}
+
+// One level of backward induction: given the value of the continuation
+// game (`continuation`, indexed by next state), returns the best action
+// for `state` this round and the value it attains.
+fn best_response_choose(
+    model: &[f64; NUM_STATES],
+    noise: f64,
+    continuation: &[f64; NUM_STATES],
+    state: usize,
+) -> (Move, f64) {
+    let moves = [Move::Cooperate, Move::Defect];
+    let mut best_action = Move::Cooperate;
+    let mut best_value = f64::NEG_INFINITY;
+    for &a in &moves {
+        let mut total = 0.;
+        for &my_noisy in &moves {
+            let my_prob = if my_noisy == a { 1. - noise } else { noise };
+            for &their_noisy in &moves {
+                let their_prob = if their_noisy == Move::Cooperate {
+                    model[state]
+                } else {
+                    1. - model[state]
+                };
+                let (my_score, _) = single_score(my_noisy, their_noisy);
+                // The next state is keyed on the *intended* move `a`, not the
+                // simulated noisy outcome `my_noisy`: `observe`/`last_state` never
+                // see a player's own noisy move (see play_round), so the model and
+                // the DP must agree on that definition.
+                let next_state = reactive_state_index(a, their_noisy);
+                total += my_prob * their_prob * (my_score as f64 + continuation[next_state]);
+            }
+        }
+        if total > best_value {
+            best_value = total;
+            best_action = a;
+        }
+    }
+    (best_action, best_value)
+}
+
+// Finite-horizon backward induction against `model`, returning the DP value
+// table for every horizon from 0 (no rounds left) up to `horizon` rounds
+// left. table[h][s] is the value of playing optimally for h more rounds
+// starting from state s.
+fn best_response_value_table(
+    model: &[f64; NUM_STATES],
+    noise: f64,
+    horizon: usize,
+) -> Vec<[f64; NUM_STATES]> {
+    let mut table = Vec::with_capacity(horizon + 1);
+    table.push([0.; NUM_STATES]);
+    for _ in 1..=horizon {
+        let continuation = *table.last().unwrap();
+        let mut value = [0.; NUM_STATES];
+        for (s, v) in value.iter_mut().enumerate() {
+            *v = best_response_choose(model, noise, &continuation, s).1;
+        }
+        table.push(value);
+    }
+    table
+}
+
+// Wall-clock budget and search parameters for `optimize_reactive`.
+const SA_BUDGET: Duration = Duration::from_secs(30);
+const SA_REPS: usize = 5;
+const SA_INITIAL_TEMP: f64 = 1.;
+const SA_FINAL_TEMP: f64 = 1e-3;
+const SA_STEP: f64 = 0.2;
+
+// Evaluates a candidate reactive strategy by playing it against pool reps
+// times and averaging its average_scores entry.
+fn evaluate_reactive(candidate: &Reactive, pool: &Vec<&Player>, rng: &mut XorShift, reps: usize) -> f64 {
+    let mut players = pool.clone();
+    players.push(candidate);
+    let mut total = 0.;
+    for _ in 0..reps {
+        let scores = play_all_pairs(&players, rng);
+        total += average_scores(&scores)[players.len() - 1];
+    }
+    total / reps as f64
+}
+
+// Simulated annealing over the parameter space of Reactive, maximizing its
+// average score against pool until SA_BUDGET elapses.
+fn optimize_reactive(pool: &Vec<&Player>, rng: &mut XorShift) -> (Reactive, f64) {
+    let start = Instant::now();
+    let mut current = Reactive::random(rng);
+    let mut current_score = evaluate_reactive(&current, pool, rng, SA_REPS);
+    let mut best = current.clone();
+    let mut best_score = current_score;
+    while start.elapsed() < SA_BUDGET {
+        let elapsed_frac = start.elapsed().as_secs_f64() / SA_BUDGET.as_secs_f64();
+        let temp = SA_INITIAL_TEMP * (SA_FINAL_TEMP / SA_INITIAL_TEMP).powf(elapsed_frac);
+        let candidate = current.perturb(rng, SA_STEP);
+        let candidate_score = evaluate_reactive(&candidate, pool, rng, SA_REPS);
+        let delta = candidate_score - current_score;
+        if delta > 0. || rng.gen_f64() < (delta / temp).exp() {
+            current = candidate;
+            current_score = candidate_score;
+            if current_score > best_score {
+                best = current.clone();
+                best_score = current_score;
+            }
+        }
+    }
+    (best, best_score)
+}
+
+// Generation count and mutation rate for `run_replicator_dynamics`.
+const NUM_GENERATIONS: usize = 500;
+const MUTATION_RATE: f64 = 1e-3;
+const CONVERGENCE_EPSILON: f64 = 1e-6;
+
+// Applies one generation of the discrete replicator dynamic to shares under
+// the payoff matrix scores, folding in a small uniform mutation term.
+fn replicator_step(scores: &Vec<Vec<u64>>, shares: &Vec<f64>, mutation_rate: f64) -> Vec<f64> {
+    let n = shares.len();
+    let fitness: Vec<f64> = (0..n)
+        .map(|i| {
+            scores[i]
+                .iter()
+                .zip(shares)
+                .map(|(&s, &x)| s as f64 * x)
+                .sum()
+        })
+        .collect();
+    let mean_fitness: f64 = fitness.iter().zip(shares).map(|(&f, &x)| f * x).sum();
+    let updated: Vec<f64> = shares
+        .iter()
+        .zip(&fitness)
+        .map(|(&x, &f)| x * f / mean_fitness)
+        .collect();
+    let total: f64 = updated.iter().sum();
+    updated
+        .iter()
+        .map(|&x| (1. - mutation_rate) * (x / total) + mutation_rate / n as f64)
+        .collect()
+}
+
+// Evolves population shares from a uniform mix for `generations` generations
+// of the replicator dynamic.
+fn run_replicator_dynamics(scores: &Vec<Vec<u64>>, generations: usize, mutation_rate: f64) -> Vec<f64> {
+    let n = scores.len();
+    let mut shares = vec![1. / n as f64; n];
+    for _ in 0..generations {
+        shares = replicator_step(scores, &shares, mutation_rate);
+    }
+    shares
+}
+
+// Classifies the long-run behavior by taking one more step from shares and
+// checking whether the population mix held still.
+fn classify_dynamics(scores: &Vec<Vec<u64>>, shares: &Vec<f64>, mutation_rate: f64) -> &'static str {
+    let next = replicator_step(scores, shares, mutation_rate);
+    let delta: f64 = shares.iter().zip(&next).map(|(&a, &b)| (a - b).abs()).sum();
+    if delta < CONVERGENCE_EPSILON {
+        "converged to a fixed point"
+    } else {
+        "still cycling / evolving"
+    }
 }
 
 const PLAYS: usize = 20;
 fn main() {
+    let mut rng = XorShift::new(SEED);
     let (c1, c2, c3, c4, c5) = (
         Constant::new(0.),
         Constant::new(0.125),
@@ -211,12 +603,13 @@ fn main() {
         Threshold::new(20, 0.5),
         Threshold::new(20, 0.7),
     );
+    let br1 = BestResponse::new(0.25);
     let players: Vec<&Player> = vec![
-        &c1, &c2, &c3, &c4, &c5, &tt1, &tt2, &tt3, &tt4, &th1, &th2, &th3, &th4,
+        &c1, &c2, &c3, &c4, &c5, &tt1, &tt2, &tt3, &tt4, &th1, &th2, &th3, &th4, &br1,
     ];
     let mut overall_ranks = vec![0.; players.len()];
     for _ in 0..PLAYS {
-        let scores = play_all_pairs(&players);
+        let scores = play_all_pairs(&players, &mut rng);
         let page_ranks = page_rank(&scores);
         for (overall_rank, rank) in overall_ranks.iter_mut().zip(page_ranks) {
             *overall_rank += rank
@@ -227,4 +620,23 @@ fn main() {
     for (player, rank) in players_and_ranks {
         println!("{:?}: {:.6}", player, rank);
     }
+
+    let (best_reactive, best_score) = optimize_reactive(&players, &mut rng);
+    println!(
+        "Best reactive strategy found by simulated annealing: {:?} (avg score {:.6})",
+        best_reactive, best_score
+    );
+
+    let ecology_scores = play_all_pairs(&players, &mut rng);
+    let shares = run_replicator_dynamics(&ecology_scores, NUM_GENERATIONS, MUTATION_RATE);
+    let dynamics = classify_dynamics(&ecology_scores, &shares, MUTATION_RATE);
+    println!(
+        "Population shares after {} generations of replicator dynamics ({}):",
+        NUM_GENERATIONS, dynamics
+    );
+    let mut players_and_shares: Vec<(&Player, f64)> = players.iter().cloned().zip(shares).collect();
+    players_and_shares.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    for (player, share) in players_and_shares {
+        println!("{:?}: {:.6}", player, share);
+    }
 }